@@ -4,14 +4,17 @@
 //! assignments inside this module, so most schnorrkell logic gets
 //! isolated here.
 //!
-//! TODO: We should expand RelayVRFModulo to do rejection sampling
-//! using `vrf::vrf_merge`, which requires `Vec<..>`s for
-//! `AssignmentSigned::vrf_preout` and `Assignment::vrf_inout`.
+//! `RelayVRFModulo` draws several samples under one relay VRF story
+//! and batches their DLEQ proofs into a single proof via
+//! `vrf::vrf_merge`, which is why `AssignmentSigned::vrf_preout` and
+//! `Assignment::vrf_inout` are `Vec<..>`s: every criteria carries at
+//! least one VRF pre-output, and `RelayVRFModulo` may carry several.
 
 use core::{borrow::Borrow, convert::TryFrom};
 
 use merlin::Transcript;
 use schnorrkel::{PublicKey, PUBLIC_KEY_LENGTH, Keypair, vrf};
+use parity_scale_codec::{Encode, Decode, Input, Output, Error as CodecError};
 
 // pub use sp_consensus_vrf::schnorrkel::{Randomness, VRF_PROOF_LENGTH, VRF_OUTPUT_LENGTH, RANDOMNESS_LENGTH };
 
@@ -51,16 +54,37 @@ pub trait Criteria : Clone + 'static {
     /// Additionl data required for constructing the VRF input
     type Story;
 
-    /// Write the transcript from which build the VRF input.  
+    /// Write the transcript from which build the VRF input.
     ///
     /// Cannot error unless `Criteria = RelayEquivocation`
     fn vrf_input(&self, story: &Self::Story) -> AssignmentResult<Transcript>;
 
+    /// Write one transcript per VRF pre-output this criteria requires.
+    ///
+    /// Almost every criteria needs only the one transcript from
+    /// `vrf_input`, so we default to that, but `RelayVRFModulo` draws
+    /// several samples under one story and overrides this to return
+    /// one transcript per sample, all batched under a single DLEQ proof.
+    fn vrf_inputs(&self, story: &Self::Story) -> AssignmentResult<Vec<Transcript>> {
+        Ok(vec![self.vrf_input(story) ?])
+    }
+
+    /// Check any criteria-specific claim against the reattached VRF
+    /// pre-output(s), beyond what the DLEQ proof itself covers.
+    ///
+    /// Most criteria need nothing further, but `RelayVRFModuloCompact`
+    /// uses this to reject a claimed bitfield that disagrees with what
+    /// its VRF output actually draws.
+    fn check_position(&self, context: &ApprovalContext, vrf_inout: &[vrf::VRFInOut]) -> AssignmentResult<()> {
+        let _ = (context, vrf_inout);
+        Ok(())
+    }
+
     /// Initialize the transcript for our Schnorr DLEQ proof.
     ///
     /// Any criteria data that requires authentication, which should make
     /// signing gossip messages unecessary, saving 64 bytes, etc.
-    fn extra(&self, context: &ApprovalContext) -> Transcript { 
+    fn extra(&self, context: &ApprovalContext) -> Transcript {
         context.transcript()
     }
 
@@ -68,25 +92,72 @@ pub trait Criteria : Clone + 'static {
 }
 
 
-/// Initial approval checker assignment based upon checkers' VRF 
+/// Initial approval checker assignment based upon checkers' VRF
 /// applied to the relay chain VRF, but then computed modulo the
 /// number of parachains.
-#[derive(Clone)]
+///
+/// We may draw several `samples`, each reduced modulo the number of
+/// parachains independently, but all proven together by one merged
+/// DLEQ proof (see `vrf::vrf_merge`), so a checker who samples many
+/// cores still only ever sends one signature.
+#[derive(Clone, Encode, Decode)]
 pub struct RelayVRFModulo {
-    pub(crate) sample: u16,
+    pub(crate) samples: Vec<u16>,
     // Story::anv_rc_vrf_source
 }
 
+impl RelayVRFModulo {
+    /// The most samples the protocol lets one batch draw.
+    pub const MAX_SAMPLES: usize = 32;
+
+    /// Transcript for one specific sample, appending the sample index
+    /// exactly as a single-sample `RelayVRFModulo` always has.
+    fn sample_input(story: &stories::RelayVRFStory, sample: u16) -> Transcript {
+        let mut t = Transcript::new(b"Approval Assignment VRF");
+        t.append_message(b"RelayVRFModulo", &story.anv_rc_vrf_source );
+        t.append_u64(b"RelayVRFModulo", sample.into() );
+        t
+    }
+
+    /// Check `samples` is exactly the canonical `0..n` protocol set:
+    /// non-empty, bounded by `MAX_SAMPLES`, and sequential from zero.
+    ///
+    /// `samples` is attacker-chosen data folded straight into each
+    /// sample's transcript, so without this check a checker could
+    /// grind arbitrary sample values until one reduces to a core of
+    /// their choosing and self-assign.  Canonicalizing it removes that
+    /// degree of freedom entirely.
+    fn check_samples(&self) -> AssignmentResult<()> {
+        if self.samples.is_empty() {
+            return Err(Error::BadAssignment("RelayVRFModulo requires at least one sample"));
+        }
+        if self.samples.len() > Self::MAX_SAMPLES {
+            return Err(Error::BadAssignment("RelayVRFModulo has too many samples"));
+        }
+        if self.samples.iter().enumerate().any(|(i, &sample)| sample as usize != i) {
+            return Err(Error::BadAssignment("RelayVRFModulo samples must be the canonical 0..n set"));
+        }
+        Ok(())
+    }
+}
+
 impl Criteria for RelayVRFModulo {
     type Story = stories::RelayVRFStory;
 
-    /// Never errors.
+    /// Errors unless `samples` is the canonical `0..n` set.  Transcript
+    /// for our first sample; use `vrf_inputs` to obtain the transcript
+    /// for every sample in this batch.
     fn vrf_input(&self, story: &Self::Story) -> AssignmentResult<Transcript> {
-        if self.sample > 0 { return Err(Error::BadAssignment("RelayVRFModulo does not yet support additional samples")); }
-        let mut t = Transcript::new(b"Approval Assignment VRF");
-        t.append_message(b"RelayVRFModulo", &story.anv_rc_vrf_source );
-        t.append_u64(b"RelayVRFModulo", self.sample.into() );
-        Ok(t)
+        self.check_samples() ?;
+        Ok(Self::sample_input(story, self.samples[0]))
+    }
+
+    /// Errors unless `samples` is the canonical `0..n` set.  One
+    /// transcript per sample, appending each sample index as
+    /// `vrf_input` would for a lone sample.
+    fn vrf_inputs(&self, story: &Self::Story) -> AssignmentResult<Vec<Transcript>> {
+        self.check_samples() ?;
+        Ok(self.samples.iter().map(|&sample| Self::sample_input(story, sample)).collect())
     }
 }
 
@@ -96,7 +167,7 @@ impl Criteria for RelayVRFModulo {
 /// Approval checker assignment based upon checkers' VRF applied
 /// to the relay chain VRF and parachain id, but then outputing a
 /// delay.  Applies only if too few check before reaching the delay.
-#[derive(Clone)]
+#[derive(Clone, Encode, Decode)]
 pub struct RelayVRFDelay {
     // Story::anv_rc_vrf_source
     pub(crate) paraid: ParaId, 
@@ -119,7 +190,7 @@ impl Criteria for RelayVRFDelay {
 
 /// Approval checker assignment based upon parablock hash
 /// of a candidate equivocation.
-#[derive(Clone)]
+#[derive(Clone, Encode, Decode)]
 pub struct RelayEquivocation {
     // Story::anv_rc_vrf_source
     pub(crate) paraid: ParaId, 
@@ -155,10 +226,13 @@ pub struct Assignment<C: Criteria, K = AssignmentSignature> {
     criteria: C,
     /// Assignment's VRF signature including its checker's key
     vrf_signature: K,
-    /// VRFInOut from which we compute the actualy assignment details
+    /// VRFInOuts from which we compute the actualy assignment details,
+    /// one per VRF pre-output this criteria carries.  Almost every
+    /// criteria has exactly one; `RelayVRFModulo` may have several,
+    /// all proven together by one merged DLEQ proof.
     /// We could save some space by storing a `VRFPreOut` in
     /// `VRFSignature`, and storing some random output here.
-    vrf_inout: vrf::VRFInOut,
+    vrf_inout: Vec<vrf::VRFInOut>,
 }
 
 impl<C> Assignment<C> where C: Criteria {
@@ -170,7 +244,7 @@ impl<C> Assignment<C> where C: Criteria {
         AssignmentSigned {
             context,
             criteria: self.criteria.clone(),
-            vrf_preout: self.vrf_inout.to_output().to_bytes(),
+            vrf_preout: self.vrf_inout.iter().map(|io| io.to_output().to_bytes()).collect(),
             vrf_signature: self.vrf_signature.clone(),
         }
     }
@@ -178,24 +252,39 @@ impl<C> Assignment<C> where C: Criteria {
 
 impl<C> Assignment<C,()> where C: Criteria {
     /// Create our own `Assignment` for the given criteria, story,
-    /// and our keypair, by constructing its `VRFInOut`.
+    /// and our keypair, by constructing one `VRFInOut` per pre-output
+    /// this criteria requires.
     pub fn create(criteria: C, story: &C::Story, checker: &Keypair) -> AssignmentResult<Assignment<C,()>> {
-        let vrf_inout = checker.borrow().vrf_create_hash(criteria.vrf_input(story) ?);
+        let vrf_inout = criteria.vrf_inputs(story) ?
+            .into_iter()
+            .map(|t| checker.borrow().vrf_create_hash(t))
+            .collect();
         Ok(Assignment { criteria, vrf_signature: (), vrf_inout, })
     }
 
     /// VRF sign our assignment for announcment.
     ///
+    /// When our criteria carries several `VRFInOut`s, we merge them
+    /// via `vrf::vrf_merge` into one and prove only that, so a batch
+    /// of samples costs one DLEQ proof instead of many.
+    ///
     /// We could take `K: Borrow<Keypair>` above in `create`, saving us
     /// the `checker` argument here, and making `K=Arc<Keypair>` work,
     /// except `Assignment`s always occur with so much repetition that
     /// passing the `Keypair` again makes more sense.
     pub fn sign(&self, context: &ApprovalContext, checker: &Keypair) -> Assignment<C> {
         let Assignment { criteria, vrf_signature: (), vrf_inout } = self;
-        // Must exactly mirror `schnorrkel::Keypair::vrf_sign_extra`
-        // or else rerun one point multiplicaiton in vrf_create_hash
+        // We merge every sample's `VRFInOut` into one before proving,
+        // so this deliberately does *not* mirror a lone
+        // `schnorrkel::Keypair::vrf_sign_extra` call; `verify` and
+        // `verify_batch` must keep reattaching and merging the same way.
+        //
+        // We keep the batchable proof, not its compact `.shrink()`, so
+        // `verify_batch` can combine many certs into one DLEQ check
+        // without first redoing this same point multiplication per cert.
+        let merged = vrf::vrf_merge(&mut Transcript::new(b"Approval Assignment VRF Merge"), vrf_inout);
         let t = criteria.extra(context);
-        let vrf_proof = checker.dleq_proove(t, vrf_inout, vrf::KUSAMA_VRF).0.to_bytes();
+        let vrf_proof = checker.dleq_proove(t, &merged, vrf::KUSAMA_VRF).1.to_bytes();
         let checker = validator_id_from_key(&checker.public);
         let vrf_signature = AssignmentSignature { checker, vrf_proof, };
         Assignment { criteria: criteria.clone(), vrf_signature, vrf_inout: vrf_inout.clone(), }
@@ -203,22 +292,76 @@ impl<C> Assignment<C,()> where C: Criteria {
 }
 
 
-/// Assignment's VRF signature.  
+/// Length of a `VRFProofBatchable` once encoded: the usual compact
+/// `(challenge, response)` pair schnorrkel's `VRF_PROOF_LENGTH` covers,
+/// plus the extra commitment point batch verification needs so it
+/// never has to redo a per-proof point multiplication to recover it.
+const VRF_PROOF_BATCHABLE_LENGTH: usize = vrf::VRF_PROOF_LENGTH + 32;
+
+/// Assignment's VRF signature.
+///
+/// We keep the proof in its batchable form, not schnorrkel's compact
+/// `VRFProof`, so `AssignmentSigned::verify_batch` can verify many
+/// certs with one combined DLEQ check instead of one `dleq_verify`
+/// call per cert; see `Assignment::sign`.
 #[derive(Clone)]
 pub struct AssignmentSignature {
     checker: ValidatorId,
-    vrf_proof: [u8; vrf::VRF_PROOF_LENGTH],
+    vrf_proof: [u8; VRF_PROOF_BATCHABLE_LENGTH],
+}
+
+// `vrf_proof` is larger than the fixed-size arrays `parity_scale_codec`
+// gives a blanket impl for, so we encode it as raw bytes by hand
+// rather than deriving.
+impl Encode for AssignmentSignature {
+    fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+        self.checker.encode_to(dest);
+        dest.write(&self.vrf_proof);
+    }
+}
+
+impl Decode for AssignmentSignature {
+    fn decode<I: Input>(input: &mut I) -> Result<Self, CodecError> {
+        let checker = ValidatorId::decode(input) ?;
+        let mut vrf_proof = [0u8; VRF_PROOF_BATCHABLE_LENGTH];
+        input.read(&mut vrf_proof) ?;
+        Ok(AssignmentSignature { checker, vrf_proof })
+    }
 }
 
 
 /// Announcable VRF signed assignment
+#[derive(Encode, Decode)]
 pub struct AssignmentSigned<C: Criteria> {
     context: ApprovalContext,
     criteria: C,
-    vrf_preout: [u8; vrf::VRF_OUTPUT_LENGTH],
+    vrf_preout: Vec<[u8; vrf::VRF_OUTPUT_LENGTH]>,
     vrf_signature: AssignmentSignature,
 }
 
+// `ApprovalContext` is defined alongside the rest of the approval
+// voting types, but we give it its own codec impl here next to the
+// other gossip-facing types this module needs to encode.
+impl Encode for ApprovalContext {
+    fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+        self.hash.encode_to(dest);
+        self.slot.encode_to(dest);
+        self.epoch.encode_to(dest);
+        self.authority.encode_to(dest);
+    }
+}
+
+impl Decode for ApprovalContext {
+    fn decode<I: Input>(input: &mut I) -> Result<Self, CodecError> {
+        Ok(ApprovalContext {
+            hash: Hash::decode(input) ?,
+            slot: u64::decode(input) ?,
+            epoch: u64::decode(input) ?,
+            authority: ValidatorId::decode(input) ?,
+        })
+    }
+}
+
 impl<C: Criteria> AssignmentSigned<C> {
     pub fn checker(&self) -> &ValidatorId { &self.vrf_signature.checker }
 
@@ -230,22 +373,95 @@ impl<C: Criteria> AssignmentSigned<C> {
     }
 
     /// Verify a signed assignment
+    ///
+    /// Reattaches every pre-output to its own per-sample input
+    /// transcript, merges them exactly as `sign` did, and checks the
+    /// single merged DLEQ proof.
     pub fn verify(&self, story: &C::Story)
-     -> AssignmentResult<(&ApprovalContext,Assignment<C,AssignmentSignature>)> 
+     -> AssignmentResult<(&ApprovalContext,Assignment<C,AssignmentSignature>)>
     {
         let AssignmentSigned { context, criteria, vrf_preout, vrf_signature  } = self;
         let checker_pk = self.checker_pk() ?;
-        let vrf_inout = vrf::VRFOutput::from_bytes(vrf_preout)
-            .expect("length enforced statically")
-            .attach_input_hash(&checker_pk, criteria.vrf_input(story) ?)
-            .map_err(|_| Error::BadAssignment("Bad VRF signature (bad pre-output)")) ?;
-        let vrf_proof = vrf::VRFProof::from_bytes(&vrf_signature.vrf_proof)
+        let inputs = criteria.vrf_inputs(story) ?;
+        if inputs.len() != vrf_preout.len() {
+            return Err(Error::BadAssignment("Wrong number of VRF pre-outputs for this criteria"));
+        }
+        let vrf_inout: Vec<vrf::VRFInOut> = vrf_preout.iter().zip(inputs)
+            .map(|(preout, input)| {
+                vrf::VRFOutput::from_bytes(preout)
+                    .expect("length enforced statically")
+                    .attach_input_hash(&checker_pk, input)
+                    .map_err(|_| Error::BadAssignment("Bad VRF signature (bad pre-output)"))
+            })
+            .collect::<AssignmentResult<_>>() ?;
+        let vrf_proof = vrf::VRFProofBatchable::from_bytes(&vrf_signature.vrf_proof)
             .map_err(|_| Error::BadAssignment("Bad VRF signature (bad proof)")) ?;
+        let merged = vrf::vrf_merge(&mut Transcript::new(b"Approval Assignment VRF Merge"), &vrf_inout);
         let t = criteria.extra(&context);
-        let _ = checker_pk.dleq_verify(t, &vrf_inout, &vrf_proof, vrf::KUSAMA_VRF)
+        // `dleq_verify` only needs the compact proof; `.shrink()` just
+        // drops the extra commitment point `verify_batch` relies on, so
+        // this costs no additional point multiplication.
+        let _ = checker_pk.dleq_verify(t, &merged, &vrf_proof.shrink(), vrf::KUSAMA_VRF)
             .map_err(|_| Error::BadAssignment("Bad VRF signature (invalid)")) ?;
+        criteria.check_position(&context, &vrf_inout) ?;
         Ok((context, Assignment { criteria: criteria.clone(), vrf_signature: vrf_signature.clone(), vrf_inout, }))
     }
+
+    /// Verify many assignments that all share one `story` with a single
+    /// batched DLEQ check, returning one result per cert so a caller can
+    /// tell exactly which ones are invalid.
+    ///
+    /// We reattach and merge every cert's pre-outputs just as `verify`
+    /// does, then hand every merged `VRFInOut` and its stored
+    /// `VRFProofBatchable` to one `vrf::dleq_verify_batch` call instead
+    /// of a `dleq_verify` per cert.  If the batch fails, we fall back to
+    /// verifying each cert individually so the caller learns which ones
+    /// are actually bad; schnorrkel's batch check only ever confirms or
+    /// denies the whole set, not which member failed.
+    pub fn verify_batch(certs: &[Self], story: &C::Story)
+     -> Vec<AssignmentResult<Assignment<C,AssignmentSignature>>>
+    {
+        let prepared: AssignmentResult<Vec<(Vec<vrf::VRFInOut>, vrf::VRFInOut, vrf::VRFProofBatchable, Transcript, PublicKey)>> =
+            certs.iter().map(|cert| {
+                let checker_pk = cert.checker_pk() ?;
+                let inputs = cert.criteria.vrf_inputs(story) ?;
+                if inputs.len() != cert.vrf_preout.len() {
+                    return Err(Error::BadAssignment("Wrong number of VRF pre-outputs for this criteria"));
+                }
+                let vrf_inout: Vec<vrf::VRFInOut> = cert.vrf_preout.iter().zip(inputs)
+                    .map(|(preout, input)| {
+                        vrf::VRFOutput::from_bytes(preout)
+                            .expect("length enforced statically")
+                            .attach_input_hash(&checker_pk, input)
+                            .map_err(|_| Error::BadAssignment("Bad VRF signature (bad pre-output)"))
+                    })
+                    .collect::<AssignmentResult<_>>() ?;
+                let merged = vrf::vrf_merge(&mut Transcript::new(b"Approval Assignment VRF Merge"), &vrf_inout);
+                let vrf_proof = vrf::VRFProofBatchable::from_bytes(&cert.vrf_signature.vrf_proof)
+                    .map_err(|_| Error::BadAssignment("Bad VRF signature (bad proof)")) ?;
+                let t = cert.criteria.extra(&cert.context);
+                Ok((vrf_inout, merged, vrf_proof, t, checker_pk))
+            }).collect();
+
+        let prepared = match prepared {
+            Ok(prepared) => prepared,
+            Err(_) => return certs.iter().map(|cert| cert.verify(story).map(|(_, assignment)| assignment)).collect(),
+        };
+
+        let transcripts: Vec<Transcript> = prepared.iter().map(|(_, _, _, t, _)| t.clone()).collect();
+        let merged: Vec<vrf::VRFInOut> = prepared.iter().map(|(_, merged, _, _, _)| merged.clone()).collect();
+        let proofs: Vec<vrf::VRFProofBatchable> = prepared.iter().map(|(_, _, proof, _, _)| proof.clone()).collect();
+        let publics: Vec<PublicKey> = prepared.iter().map(|(_, _, _, _, pk)| *pk).collect();
+
+        if vrf::dleq_verify_batch(&transcripts, &merged, &proofs, &publics, vrf::KUSAMA_VRF) {
+            certs.iter().zip(prepared.into_iter()).map(|(cert, (vrf_inout, _, _, _, _))| {
+                cert.criteria.check_position(&cert.context, &vrf_inout) ?;
+                Ok(Assignment { criteria: cert.criteria.clone(), vrf_signature: cert.vrf_signature.clone(), vrf_inout, })
+            }).collect()
+        } else {
+            certs.iter().map(|cert| cert.verify(story).map(|(_, assignment)| assignment)).collect()
+        }
+    }
 }
 
 
@@ -258,24 +474,44 @@ impl<C: Criteria> AssignmentSigned<C> {
 /// number of availability cores, so we might avoid passing it there
 /// in future once that number solidifies.
 pub(super) trait Position {
-    /// Assignment's  our `ParaId` from allowed `ParaId` returnned by
+    /// Assignment's `ParaId`s from allowed `ParaId` returnned by
     /// `stories::allowed_paraids`.
-    fn paraid(&self, context: &ApprovalContext) -> Option<ParaId>;
+    ///
+    /// The delay criteria assign at most one core, so they return a
+    /// one-element (or empty) `Vec`, but `RelayVRFModulo` returns one
+    /// deduplicated `ParaId` per sample, and `RelayVRFModuloCompact`
+    /// derives several cores from its single VRF output.
+    fn paraid(&self, context: &ApprovalContext) -> Vec<ParaId>;
 
     /// Always assign `RelayVRFModulo` the zeroth delay tranche
     fn delay_tranche(&self, context: &ApprovalContext) -> DelayTranche { 0 }
 }
 
-impl<K> Position for Assignment<RelayVRFModulo,K> {
-    /// Assign our `ParaId` from allowed `ParaId` returnned by
-    /// `stories::allowed_paraids`.
-    fn paraid(&self, context: &ApprovalContext) -> Option<ParaId> {
+impl<K> Assignment<RelayVRFModulo,K> {
+    /// Assign every sample's `ParaId` from the allowed `ParaId`s
+    /// returned by `stories::allowed_paraids`, rejection-sampling out
+    /// any sample whose reduction collides with a core we already
+    /// picked, so one batch never assigns a checker the same core twice.
+    pub fn paraids(&self, context: &ApprovalContext) -> Vec<ParaId> {
         // TODO: Optimize accessing this from `ApprovalContext`
         let paraids = context.paraids_by_core();
-        // We use u64 here to give a reasonable distribution modulo the number of parachains
-        let mut parachain = u64::from_le_bytes(self.vrf_inout.make_bytes::<[u8; 8]>(b"parachain"));
-        parachain %= paraids.len() as u64;  // assumes usize < u64
-        paraids[parachain as usize]
+        let mut assigned = Vec::with_capacity(self.vrf_inout.len());
+        for vrf_inout in &self.vrf_inout {
+            // We use u64 here to give a reasonable distribution modulo the number of parachains
+            let mut parachain = u64::from_le_bytes(vrf_inout.make_bytes::<[u8; 8]>(b"parachain"));
+            parachain %= paraids.len() as u64;  // assumes usize < u64
+            if let Some(paraid) = paraids[parachain as usize] {
+                if !assigned.contains(&paraid) { assigned.push(paraid); }
+            }
+        }
+        assigned
+    }
+}
+
+impl<K> Position for Assignment<RelayVRFModulo,K> {
+    /// Every sample's deduplicated `ParaId`; see `paraids`.
+    fn paraid(&self, context: &ApprovalContext) -> Vec<ParaId> {
+        self.paraids(context)
     }
 
     /// Always assign `RelayVRFModulo` the zeroth delay tranche
@@ -283,6 +519,163 @@ impl<K> Position for Assignment<RelayVRFModulo,K> {
 }
 
 
+/// A compact bitfield over availability cores.
+///
+/// `RelayVRFModuloCompact` uses this to name every core it assigns in
+/// one small wire field, rather than sending one `RelayVRFModulo` cert
+/// per core.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Encode, Decode)]
+pub struct CoreBitfield(Vec<u32>);
+
+impl CoreBitfield {
+    /// A bitfield with no cores set.
+    pub fn new() -> Self { CoreBitfield(Vec::new()) }
+
+    /// Set the bit for `core`, if not already set.
+    pub fn insert(&mut self, core: u32) {
+        let word = (core / 32) as usize;
+        if word >= self.0.len() { self.0.resize(word + 1, 0); }
+        self.0[word] |= 1 << (core % 32);
+    }
+
+    /// Is the bit for `core` set?
+    pub fn contains(&self, core: u32) -> bool {
+        let word = (core / 32) as usize;
+        self.0.get(word).map_or(false, |bits| bits & (1 << (core % 32)) != 0)
+    }
+
+    /// Iterate the set core indices, ascending.
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.0.iter().enumerate().flat_map(|(word, bits)| {
+            let bits = *bits;
+            (0..32u32).filter(move |b| bits & (1 << b) != 0).map(move |b| (word as u32) * 32 + b)
+        })
+    }
+}
+
+
+/// Labels for the `make_bytes` calls `RelayVRFModuloCompact` draws from
+/// its single VRF output, one per potential sample.  `merlin` requires
+/// `&'static` labels, so we precompute a fixed table instead of
+/// formatting one per sample, which caps `num_samples` at its length.
+const RELAY_VRF_MODULO_COMPACT_LABELS: [&'static [u8]; 32] = [
+    b"core-0", b"core-1", b"core-2", b"core-3", b"core-4", b"core-5", b"core-6", b"core-7",
+    b"core-8", b"core-9", b"core-10", b"core-11", b"core-12", b"core-13", b"core-14", b"core-15",
+    b"core-16", b"core-17", b"core-18", b"core-19", b"core-20", b"core-21", b"core-22", b"core-23",
+    b"core-24", b"core-25", b"core-26", b"core-27", b"core-28", b"core-29", b"core-30", b"core-31",
+];
+
+/// Compact approval checker assignment that derives several cores from
+/// one VRF output instead of sampling `RelayVRFModulo` once per core.
+///
+/// We draw `num_samples` labelled sub-outputs from the single
+/// `VRFInOut`, reduce each modulo the number of availability cores, and
+/// collect the distinct results into `bitfield`.  This carries one
+/// pre-output plus a small bitfield on the wire, rather than one
+/// `RelayVRFModulo` cert per assigned core.
+#[derive(Clone, Encode, Decode)]
+pub struct RelayVRFModuloCompact {
+    pub(crate) num_samples: u32,
+    pub(crate) bitfield: CoreBitfield,
+    // Story::anv_rc_vrf_source
+}
+
+impl RelayVRFModuloCompact {
+    /// The most labelled sub-outputs the precomputed label table can draw.
+    pub const MAX_SAMPLES: u32 = 32;
+
+    /// The number of samples the protocol actually draws per cert.
+    ///
+    /// `num_samples` is folded straight into the VRF transcript, so it
+    /// cannot be left up to the checker: letting them pick it would
+    /// let them grind for a value whose draws reduce to a favourable
+    /// set of cores. We pin it to this one protocol-wide value instead.
+    pub const NUM_SAMPLES: u32 = 8;
+
+    /// Check `num_samples` matches the protocol value.
+    fn check_num_samples(&self) -> AssignmentResult<()> {
+        if self.num_samples != Self::NUM_SAMPLES {
+            return Err(Error::BadAssignment("RelayVRFModuloCompact num_samples must match the protocol value"));
+        }
+        Ok(())
+    }
+
+    /// Recompute the bitfield of assigned cores from a `VRFInOut`,
+    /// drawing `num_samples` labelled sub-outputs and reducing each
+    /// modulo `num_cores`.  Errors rather than truncating if
+    /// `num_samples` overruns the label table.
+    fn bitfield_from(vrf_inout: &vrf::VRFInOut, num_samples: u32, num_cores: u32) -> AssignmentResult<CoreBitfield> {
+        if num_samples > Self::MAX_SAMPLES {
+            return Err(Error::BadAssignment("RelayVRFModuloCompact num_samples exceeds the label table"));
+        }
+        let mut bitfield = CoreBitfield::new();
+        if num_cores == 0 { return Ok(bitfield); }
+        let labels = &RELAY_VRF_MODULO_COMPACT_LABELS[..num_samples as usize];
+        for label in labels {
+            let core = u32::from_le_bytes(vrf_inout.make_bytes::<[u8; 4]>(label)) % num_cores;
+            bitfield.insert(core);
+        }
+        Ok(bitfield)
+    }
+
+    /// Build a new compact assignment: construct the single VRF input,
+    /// hash it with `checker`, and bake the resulting bitfield in.
+    ///
+    /// Use this instead of the generic `Assignment::create`, which has
+    /// no way to derive `bitfield` itself and would just trust whatever
+    /// a caller passed in, possibly signing a cert whose bitfield
+    /// disagrees with its own VRF output and so fails `check_position`
+    /// for every receiver.
+    pub fn compute(story: &stories::RelayVRFStory, context: &ApprovalContext, checker: &Keypair)
+     -> AssignmentResult<Assignment<RelayVRFModuloCompact, ()>>
+    {
+        let criteria = RelayVRFModuloCompact { num_samples: Self::NUM_SAMPLES, bitfield: CoreBitfield::new() };
+        let vrf_inout = checker.borrow().vrf_create_hash(criteria.vrf_input(story) ?);
+        let bitfield = Self::bitfield_from(&vrf_inout, criteria.num_samples, context.num_cores()) ?;
+        let criteria = RelayVRFModuloCompact { bitfield, ..criteria };
+        Ok(Assignment { criteria, vrf_signature: (), vrf_inout: vec![vrf_inout] })
+    }
+}
+
+impl Criteria for RelayVRFModuloCompact {
+    type Story = stories::RelayVRFStory;
+
+    /// Errors unless `num_samples` matches the protocol value.
+    fn vrf_input(&self, story: &Self::Story) -> AssignmentResult<Transcript> {
+        self.check_num_samples() ?;
+        let mut t = Transcript::new(b"Approval Assignment VRF");
+        t.append_message(b"RelayVRFModuloCompact", &story.anv_rc_vrf_source );
+        t.append_u64(b"num_samples", self.num_samples.into() );
+        Ok(t)
+    }
+
+    /// Checks `num_samples` matches the protocol value, then that the
+    /// `bitfield` we are announcing actually matches what `num_samples`
+    /// draws from the reattached VRF output.
+    fn check_position(&self, context: &ApprovalContext, vrf_inout: &[vrf::VRFInOut]) -> AssignmentResult<()> {
+        self.check_num_samples() ?;
+        let recomputed = Self::bitfield_from(&vrf_inout[0], self.num_samples, context.num_cores()) ?;
+        if recomputed != self.bitfield {
+            return Err(Error::BadAssignment("RelayVRFModuloCompact bitfield disagrees with its VRF output"));
+        }
+        Ok(())
+    }
+}
+
+impl<K> Position for Assignment<RelayVRFModuloCompact,K> {
+    /// Every `ParaId` implied by our bitfield of assigned cores.
+    fn paraid(&self, context: &ApprovalContext) -> Vec<ParaId> {
+        let paraids = context.paraids_by_core();
+        self.criteria.bitfield.iter()
+            .filter_map(|core| paraids.get(core as usize).copied().flatten())
+            .collect()
+    }
+
+    /// Always assign `RelayVRFModuloCompact` the zeroth delay tranche
+    fn delay_tranche(&self, _context: &ApprovalContext) -> DelayTranche { 0 }
+}
+
+
 /// Approval checker assignment criteria that fully utilizes delays.
 ///
 /// We require this helper trait to help unify the handling of  
@@ -321,28 +714,124 @@ impl DelayCriteria for RelayEquivocation {
 }
 
 impl<C,K> Position for Assignment<C,K> where C: DelayCriteria {
-    /// Assign our `ParaId` from the one explicitly stored, but error 
-    /// if disallowed by `stories::allowed_paraids`.
-    ///
-    /// Errors if the paraid is not declared available here.
-    fn paraid(&self, context: &ApprovalContext) -> Option<ParaId> {
+    /// Assign our `ParaId` from the one explicitly stored, but return
+    /// none if disallowed by `stories::allowed_paraids`.
+    fn paraid(&self, context: &ApprovalContext) -> Vec<ParaId> {
         use core::ops::Deref;
         let paraid = self.criteria.paraid();
         // TODO:  Speed up!  Cores are not sorted so no binary_search here
-        if context.core_by_paraid(paraid).is_none() { return None; }
-        Some(paraid)
+        if context.core_by_paraid(paraid).is_none() { return Vec::new(); }
+        vec![paraid]
     }
 
     /// Assign our delay using our VRF output
     fn delay_tranche(&self, context: &ApprovalContext) -> DelayTranche {
-        let delay_tranche_modulus = context.num_cores() 
+        let delay_tranche_modulus = context.num_cores()
             .saturating_mul( C::delay_tranches_per_core() )
             .saturating_add( C::zeroth_delay_tranche_width() );
+        // `DelayCriteria` always carries exactly one VRF pre-output.
         // We use u64 here to give a reasonable distribution modulo the number of tranches
-        let mut delay_tranche = u64::from_le_bytes(self.vrf_inout.make_bytes::<[u8; 8]>(b"tranche"));
+        let mut delay_tranche = u64::from_le_bytes(self.vrf_inout[0].make_bytes::<[u8; 8]>(b"tranche"));
         delay_tranche %= delay_tranche_modulus as u64;
         delay_tranche.saturating_sub(C::zeroth_delay_tranche_width() as u64) as u32
     }
 }
 
 
+/// A gossipable `AssignmentSigned`, tagged with which `Criteria` it was
+/// built for.
+///
+/// `AssignmentSigned::verify` is generic over `C`, so a node receiving
+/// an announcement cannot decode one without already knowing `C`.  This
+/// enum carries that tag on the wire instead, so we decode it first and
+/// only then dispatch to the right `verify`.
+#[derive(Encode, Decode)]
+pub enum AssignmentCert {
+    RelayVRFModulo(AssignmentSigned<RelayVRFModulo>),
+    RelayVRFModuloCompact(AssignmentSigned<RelayVRFModuloCompact>),
+    RelayVRFDelay(AssignmentSigned<RelayVRFDelay>),
+    RelayEquivocation(AssignmentSigned<RelayEquivocation>),
+}
+
+impl AssignmentCert {
+    /// Identify the checker, regardless of which criteria this cert uses.
+    pub fn checker(&self) -> &ValidatorId {
+        match self {
+            AssignmentCert::RelayVRFModulo(a) => a.checker(),
+            AssignmentCert::RelayVRFModuloCompact(a) => a.checker(),
+            AssignmentCert::RelayVRFDelay(a) => a.checker(),
+            AssignmentCert::RelayEquivocation(a) => a.checker(),
+        }
+    }
+
+    /// Verify whichever criteria this cert was tagged with, dispatching
+    /// to its `AssignmentSigned::verify` with the matching story, and
+    /// returning the positioned `Assignment` so the caller can still
+    /// call `Position::paraid`/`delay_tranche` on it.
+    pub fn verify<'a>(
+        &'a self,
+        vrf_story: &stories::RelayVRFStory,
+        equivocation_story: &stories::RelayEquivocationStory,
+    ) -> AssignmentResult<(&'a ApprovalContext, VerifiedAssignment)> {
+        match self {
+            AssignmentCert::RelayVRFModulo(a) =>
+                a.verify(vrf_story).map(|(context, assignment)| (context, VerifiedAssignment::RelayVRFModulo(assignment))),
+            AssignmentCert::RelayVRFModuloCompact(a) =>
+                a.verify(vrf_story).map(|(context, assignment)| (context, VerifiedAssignment::RelayVRFModuloCompact(assignment))),
+            AssignmentCert::RelayVRFDelay(a) =>
+                a.verify(vrf_story).map(|(context, assignment)| (context, VerifiedAssignment::RelayVRFDelay(assignment))),
+            AssignmentCert::RelayEquivocation(a) =>
+                a.verify(equivocation_story).map(|(context, assignment)| (context, VerifiedAssignment::RelayEquivocation(assignment))),
+        }
+    }
+}
+
+
+/// A verified `Assignment`, still tagged with which `Criteria`
+/// produced it.
+///
+/// `AssignmentCert::verify` verifies an already decoded cert without
+/// knowing its criterion ahead of time, so it cannot simply return the
+/// generic `Assignment<C,_>` the way `AssignmentSigned::verify` does.
+/// This carries the result instead, while still exposing `Position` so
+/// callers need not match on the variant themselves.
+pub enum VerifiedAssignment {
+    RelayVRFModulo(Assignment<RelayVRFModulo, AssignmentSignature>),
+    RelayVRFModuloCompact(Assignment<RelayVRFModuloCompact, AssignmentSignature>),
+    RelayVRFDelay(Assignment<RelayVRFDelay, AssignmentSignature>),
+    RelayEquivocation(Assignment<RelayEquivocation, AssignmentSignature>),
+}
+
+impl VerifiedAssignment {
+    /// Identify the checker, regardless of which criteria this assignment uses.
+    pub fn checker(&self) -> &ValidatorId {
+        match self {
+            VerifiedAssignment::RelayVRFModulo(a) => a.checker(),
+            VerifiedAssignment::RelayVRFModuloCompact(a) => a.checker(),
+            VerifiedAssignment::RelayVRFDelay(a) => a.checker(),
+            VerifiedAssignment::RelayEquivocation(a) => a.checker(),
+        }
+    }
+}
+
+impl Position for VerifiedAssignment {
+    fn paraid(&self, context: &ApprovalContext) -> Vec<ParaId> {
+        match self {
+            VerifiedAssignment::RelayVRFModulo(a) => a.paraid(context),
+            VerifiedAssignment::RelayVRFModuloCompact(a) => a.paraid(context),
+            VerifiedAssignment::RelayVRFDelay(a) => a.paraid(context),
+            VerifiedAssignment::RelayEquivocation(a) => a.paraid(context),
+        }
+    }
+
+    fn delay_tranche(&self, context: &ApprovalContext) -> DelayTranche {
+        match self {
+            VerifiedAssignment::RelayVRFModulo(a) => a.delay_tranche(context),
+            VerifiedAssignment::RelayVRFModuloCompact(a) => a.delay_tranche(context),
+            VerifiedAssignment::RelayVRFDelay(a) => a.delay_tranche(context),
+            VerifiedAssignment::RelayEquivocation(a) => a.delay_tranche(context),
+        }
+    }
+}
+
+